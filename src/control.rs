@@ -0,0 +1,114 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::rc::Rc;
+
+use inhibit::Inhibitors;
+
+/// An action a control socket client requested that `main`'s loop has to act
+/// on, rather than something `accept_one` can satisfy on its own.
+pub enum ControlAction {
+    ForceSuspend,
+}
+
+/// A connection kept open for the lifetime of an `INHIBIT` — the inhibitor it
+/// took is released the moment this connection goes away, so a dead client
+/// (crash, `kill -9`, forgotten `UNINHIBIT`) can't wedge suspend forever.
+struct HeldClient {
+    stream: UnixStream,
+    inhibit_id: u32,
+}
+
+/// A `UnixListener` accepting clients that each send a single line command:
+/// `STATUS`, `SUSPEND`, `INHIBIT <reason>` or `UNINHIBIT <id>`.
+///
+/// `STATUS`/`SUSPEND`/`UNINHIBIT` connections are one-shot: the reply is
+/// written and the connection closes. `INHIBIT` connections are instead kept
+/// open for as long as the caller wants the inhibitor held; see `HeldClient`.
+pub struct ControlSocket {
+    listener: UnixListener,
+    held: RefCell<Vec<HeldClient>>,
+}
+
+impl ControlSocket {
+    pub fn bind(path: &str) -> io::Result<Self> {
+        // Stale socket from a previous run; bind fails otherwise.
+        let _ = fs::remove_file(path);
+        Ok(ControlSocket {
+            listener: try!(UnixListener::bind(path)),
+            held: RefCell::new(Vec::new()),
+        })
+    }
+
+    pub fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+
+    /// Fds of currently-held `INHIBIT` connections, to watch alongside the
+    /// listening socket for a disconnect.
+    pub fn held_fds(&self) -> Vec<RawFd> {
+        self.held.borrow().iter().map(|c| c.stream.as_raw_fd()).collect()
+    }
+
+    /// A held connection's fd became ready — since it never gets a request
+    /// after the initial `INHIBIT`, the only thing this can mean is the
+    /// client disconnected (clean close or read error). Release its
+    /// inhibitor either way.
+    pub fn reap_held(&self, fd: RawFd, inhibitors: &Rc<RefCell<Inhibitors>>) {
+        let mut held = self.held.borrow_mut();
+        if let Some(pos) = held.iter().position(|c| c.stream.as_raw_fd() == fd) {
+            let client = held.remove(pos);
+            debug!("Control socket inhibitor {} released: client disconnected",
+                   client.inhibit_id);
+            inhibitors.borrow_mut().release(client.inhibit_id);
+        }
+    }
+
+    /// Accept one pending connection and service its single command.
+    pub fn accept_one(&self,
+                       inhibitors: &Rc<RefCell<Inhibitors>>,
+                       status: &str)
+                       -> io::Result<Option<ControlAction>> {
+        let (stream, _) = try!(self.listener.accept());
+        let mut reader = BufReader::new(try!(stream.try_clone()));
+        let mut writer = stream;
+
+        let mut line = String::new();
+        try!(reader.read_line(&mut line));
+        let mut words = line.trim().splitn(2, ' ');
+
+        match words.next() {
+            Some("STATUS") => {
+                try!(writeln!(writer, "{}", status));
+                Ok(None)
+            }
+            Some("SUSPEND") => {
+                try!(writeln!(writer, "OK"));
+                Ok(Some(ControlAction::ForceSuspend))
+            }
+            Some("INHIBIT") => {
+                let why = words.next().unwrap_or("").to_string();
+                let id = inhibitors.borrow_mut().inhibit(why);
+                try!(writeln!(writer, "{}", id));
+                self.held.borrow_mut().push(HeldClient {
+                    stream: writer,
+                    inhibit_id: id,
+                });
+                Ok(None)
+            }
+            Some("UNINHIBIT") => {
+                if let Some(id) = words.next().and_then(|s| s.parse().ok()) {
+                    inhibitors.borrow_mut().release(id);
+                }
+                try!(writeln!(writer, "OK"));
+                Ok(None)
+            }
+            _ => {
+                try!(writeln!(writer, "ERR unknown command"));
+                Ok(None)
+            }
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::VecDeque;
+use std::fmt::Write as FmtWrite;
+use std::path::Path;
+
+use rotate::RotatingFile;
+use time::MonotonicTimeMS;
+use wakeup_sources::WakeupSource;
+
+const CAPACITY: usize = 20;
+const CLIP_FILE_CAPACITY: u64 = 64 * 1024;
+const CLIP_FILE_KEEP: usize = 4;
+
+struct Snapshot {
+    time: MonotonicTimeMS,
+    sources: Vec<WakeupSource>,
+}
+
+/// A rolling window of the last `CAPACITY` `wakeup_sources` snapshots.
+///
+/// Flushed to a rotating clip file when a suspend attempt is aborted, so
+/// operators have an after-the-fact trace of exactly what kept the machine
+/// awake instead of only the single most recent event.
+pub struct ClipBuffer {
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl ClipBuffer {
+    pub fn new() -> Self {
+        ClipBuffer { snapshots: VecDeque::with_capacity(CAPACITY) }
+    }
+
+    pub fn push(&mut self, time: MonotonicTimeMS, sources: Vec<WakeupSource>) {
+        if self.snapshots.len() == CAPACITY {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(Snapshot {
+            time: time,
+            sources: sources,
+        });
+    }
+
+    /// Render the captured window and append it to `path`, rotating the file
+    /// if needed.
+    pub fn flush(&self, path: &Path, reason: &str) {
+        let mut file = match RotatingFile::open(path, CLIP_FILE_CAPACITY, CLIP_FILE_KEEP) {
+            Ok(file) => file,
+            Err(e) => {
+                error!("Failed to open clip file {}: {}", path.display(), e);
+                return;
+            }
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(out, "--- suspend aborted: {} ---", reason);
+        for snapshot in &self.snapshots {
+            let _ = writeln!(out, "[{}]", snapshot.time);
+            for source in &snapshot.sources {
+                let _ = writeln!(out,
+                                  "  {}: active_count={} wakeup_count={} last_change={}",
+                                  source.name,
+                                  source.active_count,
+                                  source.wakeup_count,
+                                  source.last_change);
+            }
+        }
+
+        if let Err(e) = file.write_all(out.as_bytes()) {
+            error!("Failed to write clip file {}: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use std::collections::HashMap;
+
+/// Tracks live suspend inhibitors, whoever is holding them (the control
+/// socket, D-Bus clients, ...).
+///
+/// While any inhibitor is held, `main`'s loop must keep applying hysteresis
+/// and must not attempt to suspend.
+pub struct Inhibitors {
+    next_id: u32,
+    holders: HashMap<u32, String>,
+}
+
+impl Inhibitors {
+    pub fn new() -> Self {
+        Inhibitors {
+            next_id: 0,
+            holders: HashMap::new(),
+        }
+    }
+
+    /// Take an inhibitor on behalf of `why`, returning a handle that
+    /// `release` accepts to give it back.
+    pub fn inhibit(&mut self, why: String) -> u32 {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.holders.insert(id, why);
+        id
+    }
+
+    /// Release a previously taken inhibitor. A stale or unknown handle is
+    /// ignored.
+    pub fn release(&mut self, id: u32) {
+        self.holders.remove(&id);
+    }
+
+    pub fn is_inhibited(&self) -> bool {
+        !self.holders.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Inhibitors;
+
+    #[test]
+    fn fresh_set_is_not_inhibited() {
+        let inhibitors = Inhibitors::new();
+        assert!(!inhibitors.is_inhibited());
+    }
+
+    #[test]
+    fn inhibit_holds_until_released() {
+        let mut inhibitors = Inhibitors::new();
+        let id = inhibitors.inhibit("reason".to_string());
+        assert!(inhibitors.is_inhibited());
+        inhibitors.release(id);
+        assert!(!inhibitors.is_inhibited());
+    }
+
+    #[test]
+    fn releasing_one_of_several_keeps_the_rest_held() {
+        let mut inhibitors = Inhibitors::new();
+        let a = inhibitors.inhibit("a".to_string());
+        let b = inhibitors.inhibit("b".to_string());
+        inhibitors.release(a);
+        assert!(inhibitors.is_inhibited());
+        inhibitors.release(b);
+        assert!(!inhibitors.is_inhibited());
+    }
+
+    #[test]
+    fn releasing_unknown_id_is_a_noop() {
+        let mut inhibitors = Inhibitors::new();
+        let id = inhibitors.inhibit("reason".to_string());
+        inhibitors.release(id.wrapping_add(1));
+        assert!(inhibitors.is_inhibited());
+    }
+
+    #[test]
+    fn ids_are_distinct() {
+        let mut inhibitors = Inhibitors::new();
+        let a = inhibitors.inhibit("a".to_string());
+        let b = inhibitors.inhibit("b".to_string());
+        assert_ne!(a, b);
+    }
+}
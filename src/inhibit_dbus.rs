@@ -0,0 +1,164 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::os::unix::io::RawFd;
+use std::rc::Rc;
+
+use dbus::{BusType, Connection, ConnectionItem, Message, NameFlag, WatchEvent};
+use libc;
+
+use inhibit::Inhibitors;
+use poll;
+
+const DBUS_INTERFACE: &'static str = "org.freedesktop.DBus";
+const NAME_OWNER_CHANGED_MATCH: &'static str = "type='signal',interface='org.freedesktop.DBus',\
+                                                 member='NameOwnerChanged'";
+
+/// Implements the old GNOME `org.freedesktop.PowerManagement.Inhibit`
+/// interface (`Inhibit(who, why) -> u32`, `UnInhibit(id)`) rather than
+/// logind's `org.freedesktop.login1.Manager` (`Inhibit(what, who, why, mode)
+/// -> fd`, no `UnInhibit`), so existing clients written against the GNOME
+/// power management bus name can hold off autosuspend without knowing they
+/// are talking to insomniad.
+const INTERFACE: &'static str = "org.freedesktop.PowerManagement.Inhibit";
+const PATH: &'static str = "/org/freedesktop/PowerManagement/Inhibit";
+const BUS_NAME: &'static str = "org.freedesktop.PowerManagement.Inhibit";
+
+/// Exposes `Inhibit`/`UnInhibit` on the system bus and emits
+/// `PrepareForSleep` around suspend attempts — `PrepareForSleep` takes its
+/// name and boolean argument from logind's signal of the same name, but is
+/// sent from `PATH` on `INTERFACE`, so only clients matching on that
+/// interface receive it.
+///
+/// Inhibitors are released automatically if the holder's unique bus name
+/// disappears (crash, `kill -9`, clean disconnect), tracked via
+/// `NameOwnerChanged` rather than requiring an explicit `UnInhibit` — the
+/// same guarantee logind gives holders of an inhibit fd.
+pub struct DbusInhibit {
+    connection: Connection,
+    holders: RefCell<HashMap<String, Vec<u32>>>,
+}
+
+impl DbusInhibit {
+    pub fn connect() -> Result<Self, ::dbus::Error> {
+        let connection = try!(Connection::get_private(BusType::System));
+        try!(connection.register_name(BUS_NAME, NameFlag::ReplaceExisting as u32));
+        try!(connection.register_object_path(PATH));
+        try!(connection.add_match(NAME_OWNER_CHANGED_MATCH));
+        Ok(DbusInhibit {
+            connection: connection,
+            holders: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// Fds to register with the main poll loop, paired with their interest.
+    pub fn watch_fds(&self) -> Vec<(RawFd, libc::c_short)> {
+        self.connection
+            .watch_fds()
+            .iter()
+            .map(|w| {
+                let mut events = 0;
+                if w.readable() {
+                    events |= poll::READABLE;
+                }
+                if w.writable() {
+                    events |= libc::POLLOUT;
+                }
+                (w.fd(), events)
+            })
+            .collect()
+    }
+
+    /// Tell the connection a watched fd became ready, then dispatch any
+    /// method calls this produced.
+    ///
+    /// `revents` must be the raw mask `poll(2)` reported for `fd` — not just
+    /// whether it fired — since libdbus distinguishes POLLOUT/POLLERR/POLLHUP
+    /// from POLLIN and needs to see all of them to flush writes and notice a
+    /// disconnect.
+    pub fn handle(&self, fd: RawFd, revents: libc::c_short, inhibitors: &Rc<RefCell<Inhibitors>>) {
+        self.connection.watch_handle(fd, WatchEvent::from_revents(revents));
+
+        for item in self.connection.iter(0) {
+            match item {
+                ConnectionItem::MethodCall(m) => self.dispatch(m, inhibitors),
+                ConnectionItem::Signal(m) => self.handle_name_owner_changed(&m, inhibitors),
+                _ => {}
+            }
+        }
+    }
+
+    fn dispatch(&self, m: Message, inhibitors: &Rc<RefCell<Inhibitors>>) {
+        if m.interface().as_ref().map(|s| &**s) != Some(INTERFACE) {
+            return;
+        }
+
+        let reply = match m.member().as_ref().map(|s| &**s) {
+            Some("Inhibit") => {
+                let (who, why): (Option<String>, Option<String>) = m.get2();
+                let why = why.unwrap_or_default();
+                let who = who.unwrap_or_default();
+                let id = inhibitors.borrow_mut().inhibit(format!("{}: {}", who, why));
+                if let Some(sender) = m.sender() {
+                    self.holders.borrow_mut().entry(sender.to_string()).or_insert_with(Vec::new).push(id);
+                }
+                m.method_return().append1(id)
+            }
+            Some("UnInhibit") => {
+                if let Some(id) = m.get1() {
+                    inhibitors.borrow_mut().release(id);
+                    self.forget(id);
+                }
+                m.method_return()
+            }
+            _ => {
+                let name = ::dbus::ErrorName::new("org.freedesktop.DBus.Error.UnknownMethod").unwrap();
+                let msg = CString::new("Unknown method").unwrap();
+                m.error(&name, &msg)
+            }
+        };
+
+        let _ = self.connection.send(reply);
+    }
+
+    /// If a held inhibitor's owner just vanished from the bus, release it —
+    /// mirrors logind releasing an inhibit lock when the holder's fd closes.
+    fn handle_name_owner_changed(&self, m: &Message, inhibitors: &Rc<RefCell<Inhibitors>>) {
+        if m.interface().as_ref().map(|s| &**s) != Some(DBUS_INTERFACE) ||
+           m.member().as_ref().map(|s| &**s) != Some("NameOwnerChanged") {
+            return;
+        }
+
+        let (name, _old_owner, new_owner): (Option<String>, Option<String>, Option<String>) = m.get3();
+        let name = match name {
+            Some(name) => name,
+            None => return,
+        };
+
+        if new_owner.map(|o| o.is_empty()).unwrap_or(true) {
+            if let Some(ids) = self.holders.borrow_mut().remove(&name) {
+                for id in ids {
+                    debug!("D-Bus inhibitor {} released: owner {} dropped off the bus", id, name);
+                    inhibitors.borrow_mut().release(id);
+                }
+            }
+        }
+    }
+
+    /// Drop `id` from whichever holder's list still references it.
+    fn forget(&self, id: u32) {
+        let mut holders = self.holders.borrow_mut();
+        holders.retain(|_, ids| {
+            ids.retain(|&held| held != id);
+            !ids.is_empty()
+        });
+    }
+
+    /// Broadcast `PrepareForSleep(true)` just before writing `/sys/power/state`,
+    /// and `PrepareForSleep(false)` once we've come back from it.
+    pub fn emit_prepare_for_sleep(&self, sleeping: bool) {
+        if let Ok(signal) = Message::new_signal(PATH, INTERFACE, "PrepareForSleep") {
+            let _ = self.connection.send(signal.append1(sleeping));
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use libc;
+use log::{self, LogLevel, LogLevelFilter, LogMetadata, LogRecord, SetLoggerError};
+use syslog;
+
+use rotate::RotatingFile;
+
+const LOG_FILE_CAPACITY: u64 = 64 * 1024;
+const LOG_FILE_KEEP: usize = 4;
+
+/// Whether to colorize the stderr sink. `Auto` colorizes only when stderr is
+/// a tty; `Always` is what `--color` asks for.
+#[derive(Clone, Copy)]
+pub enum ColorMode {
+    Auto,
+    Always,
+}
+
+impl ColorMode {
+    fn enabled(&self) -> bool {
+        match *self {
+            ColorMode::Auto => unsafe { libc::isatty(libc::STDERR_FILENO) != 0 },
+            ColorMode::Always => true,
+        }
+    }
+}
+
+fn color_code(level: LogLevel) -> &'static str {
+    match level {
+        LogLevel::Error => "\x1b[31m",
+        LogLevel::Warn => "\x1b[33m",
+        LogLevel::Info => "\x1b[32m",
+        LogLevel::Debug => "\x1b[36m",
+        LogLevel::Trace => "\x1b[37m",
+    }
+}
+
+const COLOR_RESET: &'static str = "\x1b[0m";
+
+/// A `log::Log` implementation modeled on Fuchsia's `log_listener`: it can
+/// fan a record out to stderr (optionally colorized), syslog, and a
+/// size-rotated log file, any combination of which may be active.
+struct Logger {
+    filter: LogLevelFilter,
+    color: ColorMode,
+    syslog: Option<Mutex<Box<syslog::Logger>>>,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl log::Log for Logger {
+    fn enabled(&self, metadata: &LogMetadata) -> bool {
+        metadata.level() <= self.filter
+    }
+
+    fn log(&self, record: &LogRecord) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let line = format!("{}: {}", record.level(), record.args());
+
+        if self.color.enabled() {
+            let _ = writeln!(io::stderr(),
+                              "{}{}{}",
+                              color_code(record.level()),
+                              line,
+                              COLOR_RESET);
+        } else {
+            let _ = writeln!(io::stderr(), "{}", line);
+        }
+
+        if let Some(ref syslog) = self.syslog {
+            let syslog = syslog.lock().unwrap();
+            let _ = match record.level() {
+                LogLevel::Error => syslog.err(&line),
+                LogLevel::Warn => syslog.warning(&line),
+                LogLevel::Info => syslog.info(&line),
+                LogLevel::Debug | LogLevel::Trace => syslog.debug(&line),
+            };
+        }
+
+        if let Some(ref file) = self.file {
+            let _ = file.lock().unwrap().write_all(format!("{}\n", line).as_bytes());
+        }
+    }
+}
+
+/// Replace the global logger with one fanning out to stderr plus, if
+/// requested, syslog and/or a rotating log file. Callers that pass neither
+/// `syslog` nor `log_file` get stderr output only, colorized as `color`
+/// says.
+pub fn init(filter: LogLevelFilter,
+            color: ColorMode,
+            use_syslog: bool,
+            log_file: Option<&Path>)
+            -> Result<(), SetLoggerError> {
+    log::set_logger(move |max_level| {
+        max_level.set(filter);
+
+        let syslog = if use_syslog {
+            match syslog::unix(syslog::Facility::LOG_DAEMON) {
+                Ok(logger) => Some(Mutex::new(logger)),
+                Err(e) => {
+                    let _ = writeln!(io::stderr(), "Failed to connect to syslog: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let file = log_file.and_then(|path| {
+            match RotatingFile::open(path, LOG_FILE_CAPACITY, LOG_FILE_KEEP) {
+                Ok(file) => Some(Mutex::new(file)),
+                Err(e) => {
+                    let _ = writeln!(io::stderr(), "Failed to open log file {}: {}", path.display(), e);
+                    None
+                }
+            }
+        });
+
+        Box::new(Logger {
+            filter: filter,
+            color: color,
+            syslog: syslog,
+            file: file,
+        })
+    })
+}
@@ -1,16 +1,29 @@
+extern crate dbus;
 extern crate env_logger;
 extern crate getopts;
 extern crate libc;
 #[macro_use]
 extern crate log;
+extern crate syslog;
 
+mod control;
+mod diagnostics;
+mod inhibit;
+mod inhibit_dbus;
+mod logging;
+mod poll;
+mod rotate;
 mod time;
 mod wakeup_sources;
 
+use std::cell::RefCell;
 use std::env;
 use std::fs::{File, OpenOptions};
 use std::io::{Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
 use std::process;
+use std::rc::Rc;
 use std::thread::sleep;
 use std::time::Duration;
 
@@ -18,8 +31,16 @@ use env_logger::LogBuilder;
 use getopts::Options;
 use log::LogLevelFilter;
 
+use control::{ControlAction, ControlSocket};
+use diagnostics::ClipBuffer;
+use inhibit::Inhibitors;
+use inhibit_dbus::DbusInhibit;
+use logging::ColorMode;
 use ::time::MonotonicTimeMS;
 
+const DEFAULT_CONTROL_SOCKET: &'static str = "/run/insomniad.sock";
+const CLIP_FILE_PATH: &'static str = "/var/log/insomniad-clip.log";
+
 struct PowerState {
     state_file: Option<File>,
     state: String,
@@ -129,7 +150,10 @@ impl WakeupCount {
     }
 }
 
-fn apply_hysteresis(hysteresis: Duration) {
+/// How much longer we must wait before the hysteresis window since the most
+/// recent wakeup event has elapsed. Zero means it already has, and the poll
+/// in `main`'s loop should return immediately.
+fn hysteresis_remaining(hysteresis: Duration) -> Duration {
     let event = wakeup_sources::get_most_recent_event();
 
     if let Some(event) = event {
@@ -139,17 +163,23 @@ fn apply_hysteresis(hysteresis: Duration) {
               event.last_change,
               event.name);
         info!("Current time         {}", now);
-        assert!(now >= last);
-        let delta = now - last;
+        let delta = now.checked_sub(last).unwrap_or_else(|| {
+            warn!("Wakeup event timestamp is ahead of current time (clock skew?). \
+                   Treating it as having just happened.");
+            Duration::from_millis(0)
+        });
         info!("Delta                {:?}", delta);
 
         if delta < hysteresis {
             let delay = hysteresis - delta;
             info!("Delaying for         {:?}", delay);
-            sleep(delay);
+            delay
+        } else {
+            Duration::from_millis(0)
         }
     } else {
         info!("No wakeup sources");
+        Duration::from_millis(0)
     }
 }
 
@@ -178,6 +208,19 @@ fn main() {
                         "Will not sleep until at least this much time has elapsed since the \
                          last wakeup event"),
                 "TIMEOUT_MS");
+    opts.optopt("c",
+                "control-socket",
+                "path of the control socket clients can connect to. Defaults to \
+                 /run/insomniad.sock",
+                "PATH");
+    opts.optflag("", "syslog", "Also log to syslog");
+    opts.optopt("",
+                "log-file",
+                "Also log to PATH, rotating it once it exceeds ~64 KB",
+                "PATH");
+    opts.optflag("",
+                 "color",
+                 "Colorize stderr output by severity, even if it is not a tty");
     let matches = if let Ok(matches) = opts.parse(env::args().skip(1)) {
         matches
     } else {
@@ -187,16 +230,40 @@ fn main() {
         usage(opts);
     }
 
-    if matches.opt_present("d") {
-        log_builder.filter(None, LogLevelFilter::Debug);
-    } else if matches.opt_present("v") {
-        log_builder.filter(None, LogLevelFilter::Info);
-    } else if let Ok(log) = env::var("RUST_LOG") {
-        log_builder.parse(&log);
+    let use_syslog = matches.opt_present("syslog");
+    let log_file = matches.opt_str("log-file");
+    if use_syslog || log_file.is_some() || matches.opt_present("color") {
+        // A daemon shouldn't be hardwired to stdout/stderr; fan out to
+        // whichever of syslog/a log file were asked for, alongside stderr.
+        // RUST_LOG's module-scoped directive syntax isn't supported here,
+        // only a single overall level, same as -v/-d.
+        let filter = if matches.opt_present("d") {
+            LogLevelFilter::Debug
+        } else if matches.opt_present("v") {
+            LogLevelFilter::Info
+        } else if let Ok(log) = env::var("RUST_LOG") {
+            log.parse().unwrap_or(LogLevelFilter::Error)
+        } else {
+            LogLevelFilter::Error
+        };
+        let color = if matches.opt_present("color") {
+            ColorMode::Always
+        } else {
+            ColorMode::Auto
+        };
+        logging::init(filter, color, use_syslog, log_file.as_ref().map(Path::new)).unwrap();
     } else {
-        log_builder.filter(None, LogLevelFilter::Error);
+        if matches.opt_present("d") {
+            log_builder.filter(None, LogLevelFilter::Debug);
+        } else if matches.opt_present("v") {
+            log_builder.filter(None, LogLevelFilter::Info);
+        } else if let Ok(log) = env::var("RUST_LOG") {
+            log_builder.parse(&log);
+        } else {
+            log_builder.filter(None, LogLevelFilter::Error);
+        }
+        log_builder.init().unwrap();
     }
-    log_builder.init().unwrap();
 
     let timeout = if let Some(timeout) = matches.opt_str("t") {
         timeout.parse().unwrap_or_else(|e| {
@@ -209,25 +276,143 @@ fn main() {
     info!("Using hysteresis time of {}ms", timeout);
     let timeout = Duration::from_millis(timeout);
 
+    let control_socket_path = matches.opt_str("c")
+        .unwrap_or_else(|| DEFAULT_CONTROL_SOCKET.to_string());
+    let control = match ControlSocket::bind(&control_socket_path) {
+        Ok(control) => Some(control),
+        Err(e) => {
+            error!("Failed to bind control socket {}: {}. Continuing without it.",
+                   control_socket_path,
+                   e);
+            None
+        }
+    };
+    let inhibitors = Rc::new(RefCell::new(Inhibitors::new()));
+
+    let dbus_inhibit = match DbusInhibit::connect() {
+        Ok(dbus_inhibit) => Some(dbus_inhibit),
+        Err(e) => {
+            error!("Failed to connect to D-Bus: {}. Continuing without it.", e);
+            None
+        }
+    };
+
+    let mut clip = ClipBuffer::new();
+
     let mut state = PowerState::new(matches.opt_present("n"), matches.opt_str("s"));
     loop {
         let count = WakeupCount::get();
+        let inhibited = inhibitors.borrow().is_inhibited();
+        let remaining = hysteresis_remaining(timeout);
+        clip.push(MonotonicTimeMS::now(), wakeup_sources::get_all());
+
+        let mut sources = poll::Sources::new();
+        let wakeup_idx = sources.register(count.file.as_raw_fd(), poll::EXCEPTIONAL);
+        let control_idx = control.as_ref().map(|c| sources.register(c.as_raw_fd(), poll::READABLE));
+        let held_idxs: Vec<(usize, RawFd)> = control.as_ref()
+            .map(|c| {
+                c.held_fds()
+                    .into_iter()
+                    .map(|fd| (sources.register(fd, poll::READABLE), fd))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let dbus_idxs: Vec<(usize, RawFd)> = dbus_inhibit.as_ref()
+            .map(|d| {
+                d.watch_fds()
+                    .into_iter()
+                    .map(|(fd, events)| (sources.register(fd, events), fd))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        // While inhibited there's nothing time-based to wait for: block until
+        // some fd (wakeup event, a new control connection, a held client
+        // disconnecting, a D-Bus call) becomes ready instead of busy-looping
+        // through an already-elapsed hysteresis window every iteration.
+        let poll_timeout = if inhibited { None } else { Some(remaining) };
+        let ready = sources.poll(poll_timeout).expect("poll failed");
+        let revents_of = |idx: usize| {
+            ready.iter().find(|&&(i, _)| i == idx).map(|&(_, revents)| revents)
+        };
+
+        if revents_of(wakeup_idx).is_some() {
+            debug!("Wakeup event signaled while waiting out hysteresis. Restarting.");
+            continue;
+        }
 
-        apply_hysteresis(timeout);
+        if let Some(ref control) = control {
+            for &(idx, fd) in &held_idxs {
+                if revents_of(idx).is_some() {
+                    control.reap_held(fd, &inhibitors);
+                }
+            }
+        }
+
+        if let Some(ref dbus_inhibit) = dbus_inhibit {
+            for &(idx, fd) in &dbus_idxs {
+                if let Some(revents) = revents_of(idx) {
+                    dbus_inhibit.handle(fd, revents, &inhibitors);
+                }
+            }
+        }
+
+        let mut force_suspend = false;
+        if let (Some(idx), Some(control)) = (control_idx, control.as_ref()) {
+            if revents_of(idx).is_some() {
+                let status = format!("hysteresis_remaining={:?} inhibited={}",
+                                      remaining,
+                                      inhibitors.borrow().is_inhibited());
+                match control.accept_one(&inhibitors, &status) {
+                    Ok(Some(ControlAction::ForceSuspend)) => force_suspend = true,
+                    Ok(None) => {}
+                    Err(e) => error!("Control socket client error: {}", e),
+                }
+                // A client connected before the hysteresis window elapsed.
+                // Re-evaluate hysteresis and inhibitors from scratch unless
+                // it asked us to suspend right away.
+                if !force_suspend {
+                    continue;
+                }
+            }
+        }
+
+        // A held client or D-Bus activity woke the poll before the hysteresis
+        // window elapsed. Re-evaluate from scratch unless a control client
+        // asked us to suspend right away.
+        if !force_suspend && !ready.is_empty() {
+            continue;
+        }
+
+        if inhibitors.borrow().is_inhibited() {
+            debug!("Suspend inhibited. Not sleeping.");
+            continue;
+        }
 
         if !count.put() {
             // Wake events occurred since get(). Restart.
+            clip.flush(Path::new(CLIP_FILE_PATH), "wakeup raced count.put()");
             continue;
         }
 
         info!("Going to sleep");
+        if let Some(ref dbus_inhibit) = dbus_inhibit {
+            dbus_inhibit.emit_prepare_for_sleep(true);
+        }
         if !state.sleep() {
             info!("Non-fatal error writing to suspend state");
+            clip.flush(Path::new(CLIP_FILE_PATH), "EBUSY writing to suspend state");
+            if let Some(ref dbus_inhibit) = dbus_inhibit {
+                dbus_inhibit.emit_prepare_for_sleep(false);
+            }
             // Rate-limit suspend attempts to avoid thrashing aborted suspends
             sleep(Duration::from_secs(1));
             continue;
         }
         info!("Exited sleep");
+        if let Some(ref dbus_inhibit) = dbus_inhibit {
+            dbus_inhibit.emit_prepare_for_sleep(false);
+        }
 
         // Sleep for at least the requested timeout after wake, emulating a
         // wakeup source on resume. Normally this would be taken care of by the
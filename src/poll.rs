@@ -0,0 +1,134 @@
+use std::io;
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+use libc;
+
+/// Interest mask for a normal readable fd, e.g. a listening socket.
+pub const READABLE: libc::c_short = libc::POLLIN;
+
+/// Interest mask for `/sys/power/wakeup_count`, which signals a new wakeup
+/// event via exceptional readiness rather than POLLIN.
+pub const EXCEPTIONAL: libc::c_short = libc::POLLPRI | libc::POLLERR;
+
+/// A small `poll(2)`-backed readiness registry, in the spirit of popol's
+/// `Sources`, scoped to what this daemon needs: a handful of fds polled for
+/// readiness with a single timeout per iteration.
+pub struct Sources {
+    fds: Vec<libc::pollfd>,
+}
+
+impl Sources {
+    pub fn new() -> Self {
+        Sources { fds: Vec::new() }
+    }
+
+    /// Register a fd for the given interest, returning the index to look it
+    /// up in the `poll` result.
+    pub fn register(&mut self, fd: RawFd, events: libc::c_short) -> usize {
+        self.fds.push(libc::pollfd {
+            fd: fd,
+            events: events,
+            revents: 0,
+        });
+        self.fds.len() - 1
+    }
+
+    /// Block until a registered fd is ready or `timeout` elapses, returning
+    /// the indices (as handed out by `register`) that became ready, paired
+    /// with the `revents` mask `poll(2)` reported for each. Callers that only
+    /// care whether an fd fired can match on the index; callers that hand the
+    /// fd to another library's event loop (e.g. libdbus) need the real mask,
+    /// since POLLOUT/POLLERR/POLLHUP readiness is lost if it's discarded.
+    ///
+    /// A `None` timeout blocks indefinitely.
+    pub fn poll(&mut self, timeout: Option<Duration>) -> io::Result<Vec<(usize, libc::c_short)>> {
+        let timeout_ms = match timeout {
+            Some(d) => {
+                let ms = d.as_secs()
+                    .saturating_mul(1000)
+                    .saturating_add(d.subsec_millis() as u64);
+                ms as libc::c_int
+            }
+            None => -1,
+        };
+
+        let ret = unsafe {
+            libc::poll(self.fds.as_mut_ptr(), self.fds.len() as libc::nfds_t, timeout_ms)
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(self.fds
+            .iter()
+            .enumerate()
+            .filter(|&(_, pfd)| pfd.revents != 0)
+            .map(|(i, pfd)| (i, pfd.revents))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+    use std::os::unix::io::{FromRawFd, RawFd};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    fn pipe() -> (RawFd, RawFd) {
+        let mut fds: [libc::c_int; 2] = [0; 2];
+        assert_eq!(unsafe { libc::pipe(fds.as_mut_ptr()) }, 0);
+        (fds[0], fds[1])
+    }
+
+    #[test]
+    fn poll_with_nothing_ready_times_out_empty() {
+        let (read_fd, write_fd) = pipe();
+        let mut sources = Sources::new();
+        sources.register(read_fd, READABLE);
+
+        let ready = sources.poll(Some(Duration::from_millis(10))).unwrap();
+        assert!(ready.is_empty());
+
+        unsafe {
+            libc::close(read_fd);
+            libc::close(write_fd);
+        }
+    }
+
+    #[test]
+    fn poll_reports_the_index_and_revents_of_a_readable_fd() {
+        let (read_fd, write_fd) = pipe();
+        let mut sources = Sources::new();
+        let idx = sources.register(read_fd, READABLE);
+
+        let mut writer = unsafe { UnixStream::from_raw_fd(write_fd) };
+        writer.write_all(b"x").unwrap();
+
+        let ready = sources.poll(Some(Duration::from_millis(1000))).unwrap();
+        assert_eq!(ready.len(), 1);
+        assert_eq!(ready[0].0, idx);
+        assert_eq!(ready[0].1 & READABLE, READABLE);
+
+        unsafe {
+            libc::close(read_fd);
+        }
+    }
+
+    #[test]
+    fn register_returns_increasing_indices() {
+        let (read_fd, write_fd) = pipe();
+        let mut sources = Sources::new();
+        let first = sources.register(read_fd, READABLE);
+        let second = sources.register(write_fd, libc::POLLOUT);
+        assert_eq!(first, 0);
+        assert_eq!(second, 1);
+
+        unsafe {
+            libc::close(read_fd);
+            libc::close(write_fd);
+        }
+    }
+}
@@ -1,18 +1,17 @@
 use std::fmt::{self, Display, Formatter};
 use std::io;
 use std::num::ParseIntError;
-use std::ops::Sub;
 use std::str::FromStr;
 use std::time::Duration;
 
 use libc;
 
-fn get_monotonic() -> io::Result<libc::timespec> {
+fn get_clock(clock_id: libc::clockid_t) -> io::Result<libc::timespec> {
     let mut ts = libc::timespec {
         tv_sec: 0,
         tv_nsec: 0,
     };
-    if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) } == -1 {
+    if unsafe { libc::clock_gettime(clock_id, &mut ts) } == -1 {
         Err(io::Error::last_os_error())
     } else {
         Ok(ts)
@@ -24,17 +23,29 @@ fn get_monotonic() -> io::Result<libc::timespec> {
 ///
 /// Instant stores monotonic time internally, but is too restrictive. We cannot
 /// compare a wakeup source timestamp to it since its value is opaque.
+///
+/// Backed by `CLOCK_BOOTTIME`, not `CLOCK_MONOTONIC`: the kernel's
+/// wakeup-source `last_change` timestamps are boot-inclusive, and
+/// `CLOCK_MONOTONIC` doesn't advance across a suspend, which would make any
+/// comparison against them wrong after a resume.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct MonotonicTimeMS(u64);
 
 impl MonotonicTimeMS {
     pub fn now() -> Self {
-        let ts = get_monotonic().expect("clock_gettime failed");
+        let ts = get_clock(libc::CLOCK_BOOTTIME).expect("clock_gettime failed");
         let mut ms = ts.tv_sec as i64;
         ms *= 1000;
         ms += ts.tv_nsec / 1000 / 1000;
         MonotonicTimeMS(ms as u64)
     }
+
+    /// `self - other`, or `None` if `other` is later than `self` (e.g. due to
+    /// clock skew between the reading of a wakeup source timestamp and the
+    /// current time).
+    pub fn checked_sub(self, other: Self) -> Option<Duration> {
+        self.0.checked_sub(other.0).map(Duration::from_millis)
+    }
 }
 
 impl Display for MonotonicTimeMS {
@@ -56,11 +67,3 @@ impl From<u64> for MonotonicTimeMS {
         MonotonicTimeMS(val)
     }
 }
-
-impl Sub for MonotonicTimeMS {
-    type Output = Duration;
-
-    fn sub(self, other: Self) -> Duration {
-        Duration::from_millis(self.0 - other.0)
-    }
-}
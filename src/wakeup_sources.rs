@@ -59,21 +59,23 @@ fn test_parse_wakeup_source() {
                });
 }
 
-fn most_recent_event<R: Read>(file: R) -> Option<WakeupSource> {
-    const HEADER: &'static str = concat!("name\t\t",
-                                         "active_count\tevent_count\twakeup_count\t",
-                                         "expire_count\tactive_since\ttotal_time\t",
-                                         "max_time\tlast_change\tprevent_suspend_time");
+const HEADER: &'static str = concat!("name\t\t",
+                                     "active_count\tevent_count\twakeup_count\t",
+                                     "expire_count\tactive_since\ttotal_time\t",
+                                     "max_time\tlast_change\tprevent_suspend_time");
 
+fn parse_all<R: Read>(file: R) -> Vec<WakeupSource> {
     let mut lines = BufReader::new(file).lines();
 
     let header = lines.next().expect("No header").expect("Read header failed");
     // Make sure we're reading the file we expect
     assert_eq!(header, HEADER);
 
-    // Return the most recent
-    lines.map(|line| parse_wakeup_source(&line.expect("Read sources line failed")))
-        .max_by_key(|source| source.last_change)
+    lines.map(|line| parse_wakeup_source(&line.expect("Read sources line failed"))).collect()
+}
+
+fn most_recent_event<R: Read>(file: R) -> Option<WakeupSource> {
+    parse_all(file).into_iter().max_by_key(|source| source.last_change)
 }
 
 #[test]
@@ -86,7 +88,24 @@ fn test_most_recent() {
     assert_eq!(ws.last_change, 2229000u64.into());
 }
 
+#[test]
+fn test_parse_all() {
+    use std::io::Cursor;
+
+    let cursor = Cursor::new(&include_bytes!("wakeup_sources")[..]);
+    let sources = parse_all(cursor);
+    assert!(sources.len() > 1);
+    assert!(sources.iter().any(|source| source.name == "foo"));
+}
+
 pub fn get_most_recent_event() -> Option<WakeupSource> {
     let f = File::open("/sys/kernel/debug/wakeup_sources").expect("Failed to open wakeup_sources");
     most_recent_event(f)
 }
+
+/// Parse every row of `/sys/kernel/debug/wakeup_sources`, not just the most
+/// recently changed one, for use by diagnostics that need the full picture.
+pub fn get_all() -> Vec<WakeupSource> {
+    let f = File::open("/sys/kernel/debug/wakeup_sources").expect("Failed to open wakeup_sources");
+    parse_all(f)
+}